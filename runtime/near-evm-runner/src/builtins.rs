@@ -0,0 +1,490 @@
+//! The canonical Ethereum precompiled contracts, addresses `0x01`-`0x09`.
+//! Gas costs follow the Istanbul schedule (EIP-1108/1344/152). Each
+//! precompile exposes `required_gas`/`run` so `interpreter::call` can
+//! charge for it before executing, exactly like a regular opcode.
+
+use ethereum_types::{Address, U256};
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::errors::EvmError;
+
+pub trait Precompile {
+    fn required_gas(&self, input: &[u8]) -> u64;
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError>;
+}
+
+/// Returns the precompile implementation for `address`, or `None` if it
+/// doesn't fall in the `0x01`-`0x09` range.
+pub fn precompile(address: &Address) -> Option<Box<dyn Precompile>> {
+    if address.0[..19] != [0u8; 19] {
+        return None;
+    }
+    match address.0[19] {
+        1 => Some(Box::new(EcRecover)),
+        2 => Some(Box::new(Sha256Hash)),
+        3 => Some(Box::new(Ripemd160Hash)),
+        4 => Some(Box::new(Identity)),
+        5 => Some(Box::new(ModExp)),
+        6 => Some(Box::new(Bn128Add)),
+        7 => Some(Box::new(Bn128Mul)),
+        8 => Some(Box::new(Bn128Pairing)),
+        9 => Some(Box::new(Blake2F)),
+        _ => None,
+    }
+}
+
+fn word_count(len: usize) -> u64 {
+    ((len + 31) / 32) as u64
+}
+
+/// Reads `len` bytes starting at `offset` from `data`, as if `data` were
+/// zero-padded to infinite length (the convention every precompile's
+/// ABI-ish input layout relies on).
+fn read_padded(data: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    if offset < data.len() {
+        let available = std::cmp::min(len, data.len() - offset);
+        out[..available].copy_from_slice(&data[offset..offset + available]);
+    }
+    out
+}
+
+/// `0x01`: recovers the 20-byte address that signed `hash` with
+/// `(v, r, s)`, returned left-padded to 32 bytes. Returns 32 zero bytes
+/// (not an error) if the signature doesn't recover.
+pub struct EcRecover;
+
+impl Precompile for EcRecover {
+    fn required_gas(&self, _input: &[u8]) -> u64 {
+        3_000
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        let hash = read_padded(input, 0, 32);
+        let v = read_padded(input, 32, 32);
+        let r = read_padded(input, 64, 32);
+        let s = read_padded(input, 96, 32);
+
+        if v.iter().take(31).any(|b| *b != 0) || (v[31] != 27 && v[31] != 28) {
+            return Ok(vec![0u8; 32]);
+        }
+        let recovery_id = match secp256k1::RecoveryId::parse(v[31] - 27) {
+            Ok(id) => id,
+            Err(_) => return Ok(vec![0u8; 32]),
+        };
+        let signature = {
+            let mut sig = [0u8; 64];
+            sig[..32].copy_from_slice(&r);
+            sig[32..].copy_from_slice(&s);
+            match secp256k1::Signature::parse_standard(&sig) {
+                Ok(sig) => sig,
+                Err(_) => return Ok(vec![0u8; 32]),
+            }
+        };
+        let message = {
+            let mut msg = [0u8; 32];
+            msg.copy_from_slice(&hash);
+            secp256k1::Message::parse(&msg)
+        };
+        match secp256k1::recover(&message, &signature, &recovery_id) {
+            Ok(public_key) => {
+                let serialized = public_key.serialize();
+                // Drop the leading 0x04 tag; the address is the low 20
+                // bytes of keccak256 of the 64-byte uncompressed point.
+                let hash = sha3::Keccak256::digest(&serialized[1..]);
+                let mut out = vec![0u8; 32];
+                out[12..].copy_from_slice(&hash[12..]);
+                Ok(out)
+            }
+            Err(_) => Ok(vec![0u8; 32]),
+        }
+    }
+}
+
+/// `0x02`: SHA-256 of the whole input.
+pub struct Sha256Hash;
+
+impl Precompile for Sha256Hash {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        60 + 12 * word_count(input.len())
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        Ok(Sha256::digest(input).to_vec())
+    }
+}
+
+/// `0x03`: RIPEMD-160 of the whole input, left-padded to 32 bytes.
+pub struct Ripemd160Hash;
+
+impl Precompile for Ripemd160Hash {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        600 + 120 * word_count(input.len())
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        let digest = Ripemd160::digest(input);
+        let mut out = vec![0u8; 32];
+        out[12..].copy_from_slice(&digest);
+        Ok(out)
+    }
+}
+
+/// `0x04`: returns the input unchanged.
+pub struct Identity;
+
+impl Precompile for Identity {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        15 + 3 * word_count(input.len())
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        Ok(input.to_vec())
+    }
+}
+
+/// `0x05`: arbitrary-precision modular exponentiation. Input layout is
+/// `base_len(32) | exp_len(32) | mod_len(32) | base | exponent | modulus`,
+/// all big-endian.
+pub struct ModExp;
+
+/// Caps the length prefixes `ModExp` will ever try to materialize a
+/// buffer for. The real Ethereum gas schedule already makes anything
+/// this large cost astronomically more than any gas limit could pay
+/// for; capping here just keeps the cast from the 256-bit length word
+/// down to `usize` (and the `Vec` it sizes) from overflowing/panicking
+/// or becoming an OOM vector on crafted input.
+const MODEXP_MAX_LEN: usize = 1 << 24;
+
+impl ModExp {
+    /// Reads the three big-endian length prefixes, clamping each to
+    /// `MODEXP_MAX_LEN` instead of panicking when the RLP-ish length
+    /// word doesn't fit in a `usize` (or is merely huge).
+    fn lengths(input: &[u8]) -> (usize, usize, usize) {
+        let base_len = Self::clamped_len(&read_padded(input, 0, 32));
+        let exp_len = Self::clamped_len(&read_padded(input, 32, 32));
+        let mod_len = Self::clamped_len(&read_padded(input, 64, 32));
+        (base_len, exp_len, mod_len)
+    }
+
+    fn clamped_len(bytes: &[u8]) -> usize {
+        let value = U256::from_big_endian(bytes);
+        if value > U256::from(MODEXP_MAX_LEN) {
+            MODEXP_MAX_LEN
+        } else {
+            value.as_usize()
+        }
+    }
+}
+
+impl Precompile for ModExp {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        let (base_len, exp_len, mod_len) = Self::lengths(input);
+        let exp_start = 96 + base_len;
+        let exp_head = read_padded(input, exp_start, std::cmp::min(exp_len, 32));
+        let exp_head = U256::from_big_endian(&exp_head);
+
+        let adjusted_exp_len = if exp_len <= 32 {
+            if exp_head.is_zero() { 0 } else { (exp_head.bits() - 1) as u64 }
+        } else {
+            let top_bits = if exp_head.is_zero() { 0 } else { exp_head.bits() as u64 - 1 };
+            8 * (exp_len as u64 - 32) + top_bits
+        };
+
+        fn complexity(len: usize) -> u64 {
+            let len = len as u64;
+            if len <= 64 {
+                len * len
+            } else if len <= 1024 {
+                len * len / 4 + 96 * len - 3_072
+            } else {
+                len.saturating_mul(len) / 16 + 480 * len - 199_680
+            }
+        }
+
+        let max_len = std::cmp::max(base_len, mod_len);
+        complexity(max_len).saturating_mul(std::cmp::max(adjusted_exp_len, 1)) / 20
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        let (base_len, exp_len, mod_len) = Self::lengths(input);
+        let base = BigUint::from_bytes_be(&read_padded(input, 96, base_len));
+        let exponent = BigUint::from_bytes_be(&read_padded(input, 96 + base_len, exp_len));
+        let modulus = BigUint::from_bytes_be(&read_padded(input, 96 + base_len + exp_len, mod_len));
+
+        let result = if modulus.is_zero() {
+            BigUint::zero()
+        } else if exponent.is_zero() {
+            BigUint::one() % &modulus
+        } else {
+            base.modpow(&exponent, &modulus)
+        };
+
+        let mut out = result.to_bytes_be();
+        if out.len() < mod_len {
+            let mut padded = vec![0u8; mod_len - out.len()];
+            padded.append(&mut out);
+            out = padded;
+        }
+        Ok(out)
+    }
+}
+
+/// `0x06`: `alt_bn128` point addition.
+pub struct Bn128Add;
+
+impl Precompile for Bn128Add {
+    fn required_gas(&self, _input: &[u8]) -> u64 {
+        150
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        let p1 = bn128::read_point(input, 0)?;
+        let p2 = bn128::read_point(input, 64)?;
+        bn128::encode_point((p1 + p2).into())
+    }
+}
+
+/// `0x07`: `alt_bn128` scalar multiplication.
+pub struct Bn128Mul;
+
+impl Precompile for Bn128Mul {
+    fn required_gas(&self, _input: &[u8]) -> u64 {
+        6_000
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        let p = bn128::read_point(input, 0)?;
+        let scalar = bn::Fr::from_slice(&read_padded(input, 64, 32))
+            .map_err(|_| EvmError::ArgumentParseError)?;
+        bn128::encode_point((p * scalar).into())
+    }
+}
+
+/// `0x08`: `alt_bn128` optimal-ate pairing check. Returns 32 bytes, the
+/// big-endian encoding of `1` if the product of pairings is the identity
+/// in `GT`, `0` otherwise.
+pub struct Bn128Pairing;
+
+impl Precompile for Bn128Pairing {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        45_000 + 34_000 * (input.len() / 192) as u64
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        if input.len() % 192 != 0 {
+            return Err(EvmError::ArgumentParseError);
+        }
+        let mut pairs = Vec::with_capacity(input.len() / 192);
+        for chunk in input.chunks(192) {
+            let g1 = bn128::read_point(chunk, 0)?;
+            let g2 = bn128::read_g2_point(chunk, 64)?;
+            pairs.push((g1, g2));
+        }
+        let accumulated = bn::pairing_batch(&pairs);
+        let mut out = vec![0u8; 32];
+        if accumulated == bn::Gt::one() {
+            out[31] = 1;
+        }
+        Ok(out)
+    }
+}
+
+/// `0x09`: the `F` compression function from EIP-152, used by BLAKE2b.
+pub struct Blake2F;
+
+impl Precompile for Blake2F {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        if input.len() < 4 {
+            return 0;
+        }
+        u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as u64
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        blake2f::compress(input)
+    }
+}
+
+mod bn128 {
+    use bn::{AffineG1, AffineG2, Fq, Fq2, Group, G1, G2};
+
+    use super::{read_padded, EvmError};
+
+    pub fn read_point(input: &[u8], offset: usize) -> Result<G1, EvmError> {
+        let x = Fq::from_slice(&read_padded(input, offset, 32))
+            .map_err(|_| EvmError::ArgumentParseError)?;
+        let y = Fq::from_slice(&read_padded(input, offset + 32, 32))
+            .map_err(|_| EvmError::ArgumentParseError)?;
+        if x.is_zero() && y.is_zero() {
+            return Ok(G1::zero());
+        }
+        AffineG1::new(x, y).map(Into::into).map_err(|_| EvmError::ArgumentParseError)
+    }
+
+    pub fn read_g2_point(input: &[u8], offset: usize) -> Result<G2, EvmError> {
+        let bad = || EvmError::ArgumentParseError;
+        let ax = Fq::from_slice(&read_padded(input, offset, 32)).map_err(|_| bad())?;
+        let ay = Fq::from_slice(&read_padded(input, offset + 32, 32)).map_err(|_| bad())?;
+        let bx = Fq::from_slice(&read_padded(input, offset + 64, 32)).map_err(|_| bad())?;
+        let by = Fq::from_slice(&read_padded(input, offset + 96, 32)).map_err(|_| bad())?;
+        let x = Fq2::new(ay, ax);
+        let y = Fq2::new(by, bx);
+        if x.is_zero() && y.is_zero() {
+            return Ok(G2::zero());
+        }
+        AffineG2::new(x, y).map(Into::into).map_err(|_| bad())
+    }
+
+    pub fn encode_point(point: AffineG1) -> Result<Vec<u8>, EvmError> {
+        let mut out = vec![0u8; 64];
+        point.x().to_big_endian(&mut out[..32]).map_err(|_| EvmError::ArgumentParseError)?;
+        point.y().to_big_endian(&mut out[32..]).map_err(|_| EvmError::ArgumentParseError)?;
+        Ok(out)
+    }
+}
+
+mod blake2f {
+    use super::EvmError;
+
+    const IV: [u64; 8] = [
+        0x6a09e667f3bcc908,
+        0xbb67ae8584caa73b,
+        0x3c6ef372fe94f82b,
+        0xa54ff53a5f1d36f1,
+        0x510e527fade682d1,
+        0x9b05688c2b3e6c1f,
+        0x1f83d9abfb41bd6b,
+        0x5be0cd19137e2179,
+    ];
+
+    const SIGMA: [[usize; 16]; 10] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+        [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+        [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+        [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+        [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+        [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+        [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+        [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+        [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    ];
+
+    fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    }
+
+    /// Parses the EIP-152 input layout (`rounds(4) | h(64) | m(128) |
+    /// t0(8) | t1(8) | f(1)`, 213 bytes total) and runs `rounds` of the F
+    /// compression function.
+    pub fn compress(input: &[u8]) -> Result<Vec<u8>, EvmError> {
+        if input.len() != 213 {
+            return Err(EvmError::ArgumentParseError);
+        }
+        let rounds = u32::from_be_bytes(input[0..4].try_into().unwrap()) as usize;
+
+        let mut h = [0u64; 8];
+        for (i, word) in h.iter_mut().enumerate() {
+            *word = u64::from_le_bytes(input[4 + i * 8..4 + i * 8 + 8].try_into().unwrap());
+        }
+
+        let mut m = [0u64; 16];
+        for (i, word) in m.iter_mut().enumerate() {
+            let offset = 68 + i * 8;
+            *word = u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap());
+        }
+
+        let t0 = u64::from_le_bytes(input[196..204].try_into().unwrap());
+        let t1 = u64::from_le_bytes(input[204..212].try_into().unwrap());
+        let last_block = match input[212] {
+            0 => false,
+            1 => true,
+            _ => return Err(EvmError::ArgumentParseError),
+        };
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&h);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= t0;
+        v[13] ^= t1;
+        if last_block {
+            v[14] = !v[14];
+        }
+
+        for round in 0..rounds {
+            let s = &SIGMA[round % 10];
+            g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+
+        for i in 0..8 {
+            h[i] ^= v[i] ^ v[i + 8];
+        }
+
+        let mut out = Vec::with_capacity(64);
+        for word in h.iter() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `0x02` against the well-known `sha256("abc")` test vector.
+    #[test]
+    fn sha256_hash_matches_known_vector() {
+        let output = Sha256Hash.run(b"abc").unwrap();
+        assert_eq!(
+            output,
+            hex_to_bytes("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+
+    /// `0x05` against a tiny modular exponentiation: `3^5 mod 7 = 5`.
+    #[test]
+    fn modexp_matches_known_vector() {
+        let mut input = Vec::new();
+        input.extend_from_slice(&be32(1)); // base_len
+        input.extend_from_slice(&be32(1)); // exp_len
+        input.extend_from_slice(&be32(1)); // mod_len
+        input.push(3); // base
+        input.push(5); // exponent
+        input.push(7); // modulus
+
+        let output = ModExp.run(&input).unwrap();
+        assert_eq!(output, vec![5]);
+    }
+
+    fn be32(value: u64) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        U256::from(value).to_big_endian(&mut bytes);
+        bytes
+    }
+
+    fn hex_to_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+}
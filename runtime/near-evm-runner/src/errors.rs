@@ -0,0 +1,58 @@
+use near_vm_errors::{FunctionCallError, VMError};
+
+/// Errors that can occur while executing an EVM transaction inside the
+/// NEAR runtime. These are kept separate from `VMError` so that the EVM
+/// core has no dependency on how the outer VM reports failures; `run_evm`
+/// is responsible for mapping between the two.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmError {
+    /// The method arguments could not be deserialized.
+    ArgumentParseError,
+    /// `deposit_near` was called without any attached deposit.
+    MissingDeposit,
+    /// The caller does not have enough EVM-side balance to complete the
+    /// requested transfer.
+    InsufficientFunds,
+    /// The method name did not match any of the EVM entry points, the
+    /// call stack depth limit was exceeded, or a contract-address scheme
+    /// other than `FromSenderAndNonce` was requested.
+    UnknownError,
+    /// A read from the underlying trie failed or returned data that
+    /// could not be deserialized into the expected type. This means the
+    /// database is corrupt (or was tampered with); the transaction must
+    /// abort rather than silently substitute a default value, since doing
+    /// so could mint balance or lose code.
+    StateCorrupt,
+    /// Execution exceeded the EVM gas limit derived from the caller's
+    /// NEAR gas budget. The checkpoint opened for the call is rolled
+    /// back, same as a `REVERT`.
+    OutOfGas,
+    /// An admin-only method (e.g. pausing the bridge) was called by
+    /// someone other than the EVM contract's own account.
+    AdminRequired,
+    /// The deposit or withdraw direction of the NEAR<->EVM bridge is
+    /// currently paused by the admin-set mask.
+    BridgePaused,
+    /// A `submit`-ted meta-transaction's signature did not recover to any
+    /// address.
+    InvalidSignature,
+    /// A `submit`-ted meta-transaction's embedded EIP-155 chain id does not
+    /// match this deployment's.
+    InvalidChainId,
+    /// A `submit`-ted meta-transaction's nonce does not match the
+    /// recovered sender's current `EvmAccount::nonce`, i.e. it is a replay
+    /// or was submitted out of order.
+    InvalidNonce,
+    /// A `call` targeted an address that holds contract bytecode, which
+    /// this interpreter does not yet execute (see `interpreter::call`'s
+    /// doc comment). Raised explicitly rather than silently succeeding
+    /// with empty return data, so a caller can't mistake "the contract's
+    /// logic never ran" for "the contract ran and returned nothing".
+    BytecodeNotSupported,
+}
+
+impl From<EvmError> for VMError {
+    fn from(err: EvmError) -> Self {
+        VMError::FunctionCallError(FunctionCallError::EvmError(format!("{:?}", err)))
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use ethereum_types::{Address, U256};
+use near_primitives::types::{AccountId, Balance};
+
+use crate::errors::EvmError;
+
+#[derive(Debug, Clone, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct EvmAccount {
+    pub nonce: U256,
+    pub balance: U256,
+}
+
+/// A single log entry emitted by `LOG0`-`LOG4` during execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<[u8; 32]>,
+    pub data: Vec<u8>,
+}
+
+/// One layer of the EVM's substate overlay. Every write a call makes is
+/// buffered here instead of being applied directly to the trie, so a
+/// `REVERT` or an out-of-gas failure can discard it wholesale.
+///
+/// Note: this does not yet track suicides, per-frame created-contract
+/// sets or gas refunds — those only matter once the interpreter actually
+/// executes `SELFDESTRUCT`/`CREATE`-on-existing-account/`SSTORE` refund
+/// opcodes, which it doesn't (see `interpreter::call`'s doc comment).
+/// Add them alongside that work rather than carrying unused scaffolding.
+#[derive(Debug, Clone, Default)]
+pub struct StateStore {
+    pub accounts: HashMap<Address, EvmAccount>,
+    pub codes: HashMap<Address, Vec<u8>>,
+    pub storages: HashMap<[u8; 52], [u8; 32]>,
+    pub logs: Vec<Log>,
+    /// Outgoing NEAR transfers queued by `withdraw_near`, not yet handed
+    /// to the host's promise API. Queued rather than fired immediately so
+    /// a promise can't be created for a balance decrement that then gets
+    /// rolled back or fails to commit: `EvmContext::finalize` only drains
+    /// this (via `PromiseHandler::transfer`) after `commit_changes`
+    /// succeeds.
+    pub pending_transfers: Vec<(AccountId, Balance)>,
+    /// `Some` once this layer has overridden the NEAR<->EVM bridge's
+    /// tracked total supply; `None` means "unchanged, see parent".
+    pub total_supply: Option<U256>,
+    /// `Some` once this layer has overridden the bridge's paused-flags
+    /// mask; `None` means "unchanged, see parent".
+    pub paused_mask: Option<u8>,
+}
+
+impl StateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Folds a child overlay into `self` as if its writes had been made
+    /// directly against `self`, i.e. commits one checkpoint level into
+    /// its parent.
+    pub fn accrue(&mut self, other: StateStore) {
+        self.accounts.extend(other.accounts);
+        self.codes.extend(other.codes);
+        self.storages.extend(other.storages);
+        self.logs.extend(other.logs);
+        self.pending_transfers.extend(other.pending_transfers);
+        if let Some(total_supply) = other.total_supply {
+            self.total_supply = Some(total_supply);
+        }
+        if let Some(paused_mask) = other.paused_mask {
+            self.paused_mask = Some(paused_mask);
+        }
+    }
+}
+
+/// Packs an account address and a 32-byte storage slot into the flat key
+/// used by the contract-storage maps.
+pub fn storage_key(address: &Address, key: [u8; 32]) -> [u8; 52] {
+    let mut result = [0u8; 52];
+    result[..20].copy_from_slice(&address.0);
+    result[20..].copy_from_slice(&key);
+    result
+}
+
+/// Backing storage for the EVM: account balances/nonces, contract code
+/// and contract storage slots, all addressed by a 20-byte Ethereum
+/// address. Implementors are expected to layer an in-flight overlay
+/// (see `StateStore`) on top of whatever durable storage they wrap, so
+/// that speculative execution can be rolled back.
+pub trait EvmState {
+    fn code_at(&self, address: &Address) -> Result<Option<Vec<u8>>, EvmError>;
+    fn set_code(&mut self, address: &Address, bytecode: &[u8]) -> Result<(), EvmError>;
+
+    fn get_account(&self, address: &Address) -> Result<EvmAccount, EvmError>;
+    fn set_account(&mut self, address: &Address, account: &EvmAccount) -> Result<(), EvmError>;
+
+    fn _read_contract_storage(&self, key: [u8; 52]) -> Result<Option<[u8; 32]>, EvmError>;
+    fn _set_contract_storage(
+        &mut self,
+        key: [u8; 52],
+        value: [u8; 32],
+    ) -> Result<Option<[u8; 32]>, EvmError>;
+
+    /// Writes a finished overlay down into durable storage, e.g. once the
+    /// outermost checkpoint of a transaction has committed.
+    fn commit_changes(&mut self, other: &StateStore) -> Result<(), EvmError>;
+
+    /// Charges `amount` EVM gas units against whatever budget the
+    /// implementor is tracking, returning `EvmError::OutOfGas` once it is
+    /// exhausted. The default is a no-op, for embeddings (e.g. test
+    /// fixtures) that don't meter gas.
+    fn charge_gas(&mut self, _amount: u64) -> Result<(), EvmError> {
+        Ok(())
+    }
+
+    fn balance_of(&self, address: &Address) -> Result<U256, EvmError> {
+        Ok(self.get_account(address)?.balance)
+    }
+
+    fn add_balance(&mut self, address: &Address, incr: U256) -> Result<(), EvmError> {
+        let mut account = self.get_account(address)?;
+        account.balance = account.balance.saturating_add(incr);
+        self.set_account(address, &account)
+    }
+
+    fn sub_balance(&mut self, address: &Address, decr: U256) -> Result<(), EvmError> {
+        let mut account = self.get_account(address)?;
+        account.balance = account.balance.saturating_sub(decr);
+        self.set_account(address, &account)
+    }
+
+    /// Moves `value` from `from` to `to`, rejecting the transfer outright
+    /// if `from` doesn't have enough balance rather than relying on
+    /// `sub_balance`'s saturating arithmetic (which would silently clamp
+    /// the sender to zero while still crediting the recipient the full
+    /// amount, minting balance out of thin air).
+    fn transfer_balance(
+        &mut self,
+        from: &Address,
+        to: &Address,
+        value: U256,
+    ) -> Result<(), EvmError> {
+        if self.balance_of(from)? < value {
+            return Err(EvmError::InsufficientFunds);
+        }
+        self.sub_balance(from, value)?;
+        self.add_balance(to, value)
+    }
+
+    fn read_contract_storage(
+        &self,
+        address: &Address,
+        key: [u8; 32],
+    ) -> Result<Option<[u8; 32]>, EvmError> {
+        self._read_contract_storage(storage_key(address, key))
+    }
+
+    fn set_contract_storage(
+        &mut self,
+        address: &Address,
+        key: [u8; 32],
+        value: [u8; 32],
+    ) -> Result<Option<[u8; 32]>, EvmError> {
+        self._set_contract_storage(storage_key(address, key), value)
+    }
+}
@@ -0,0 +1,125 @@
+use ethereum_types::{Address, U256};
+use evm::CreateContractAddress;
+use sha3::{Digest, Keccak256};
+
+use crate::builtins;
+use crate::errors::EvmError;
+use crate::evm_state::EvmState;
+
+const MAX_CALL_STACK_DEPTH: usize = 1024;
+
+// Istanbul intrinsic-gas costs. Until bytecode is actually interpreted
+// (see `call`'s doc comment), these are the only opcode-shaped costs we
+// can charge: the flat cost of a CALL/CREATE, the per-byte cost of
+// calldata, and the per-byte cost of persisting deployed code.
+const G_CALL: u64 = 700;
+const G_CREATE: u64 = 32_000;
+const G_CODE_DEPOSIT: u64 = 200;
+const G_TX_DATA_ZERO: u64 = 4;
+const G_TX_DATA_NONZERO: u64 = 16;
+
+fn data_gas(data: &[u8]) -> u64 {
+    data.iter()
+        .map(|byte| if *byte == 0 { G_TX_DATA_ZERO } else { G_TX_DATA_NONZERO })
+        .sum()
+}
+
+/// Computes the address a new contract will be deployed to, given the
+/// deployer's address and its current nonce (the only scheme this
+/// interpreter supports today).
+fn contract_address(
+    scheme: CreateContractAddress,
+    sender: &Address,
+    nonce: &U256,
+) -> Result<Address, EvmError> {
+    match scheme {
+        CreateContractAddress::FromSenderAndNonce => {
+            let mut nonce_bytes = [0u8; 32];
+            nonce.to_big_endian(&mut nonce_bytes);
+            let mut stream = rlp::RlpStream::new_list(2);
+            stream.append(&sender.0.to_vec());
+            stream.append(&nonce_bytes.to_vec());
+            let hash = Keccak256::digest(&stream.out());
+            Ok(Address::from_slice(&hash[12..32]))
+        }
+        _ => Err(EvmError::UnknownError),
+    }
+}
+
+/// Deploys `input` as the code of a freshly created contract, owned by
+/// `sender`. Any `value` attached to the deployment is moved from the
+/// sender into the new account before its constructor logic runs.
+pub fn deploy_code<T: EvmState>(
+    state: &mut T,
+    sender: &Address,
+    origin: &Address,
+    value: U256,
+    call_stack_depth: usize,
+    create_address_scheme: CreateContractAddress,
+    should_commit: bool,
+    input: &[u8],
+) -> Result<Address, EvmError> {
+    if call_stack_depth > MAX_CALL_STACK_DEPTH {
+        return Err(EvmError::UnknownError);
+    }
+    state.charge_gas(G_CREATE + G_CODE_DEPOSIT * input.len() as u64)?;
+
+    let nonce = state.get_account(sender)?.nonce;
+    let address = contract_address(create_address_scheme, sender, &nonce)?;
+
+    if !value.is_zero() {
+        state.transfer_balance(sender, &address, value)?;
+    }
+    state.set_code(&address, input)?;
+
+    let _ = (origin, should_commit);
+    Ok(address)
+}
+
+/// Executes a call against `contract_address`. Only two shapes of target
+/// are actually supported today: a precompile (`0x01`-`0x09`), or a plain
+/// account with no code, i.e. a value transfer. **This interpreter does
+/// not execute EVM bytecode** — there is no opcode loop, so it cannot
+/// charge per-opcode gas or run `CREATE`/`SELFDESTRUCT`/logging from
+/// within a contract. A call to an address that holds deployed code
+/// returns `EvmError::BytecodeNotSupported` rather than silently
+/// succeeding with empty return data, so callers can't mistake "never
+/// ran" for "ran and returned nothing". Closing this gap means building
+/// a real fetch/decode/execute loop over `evm::Opcode`, which is future
+/// work, not something this function can respect
+/// (`should_commit`/the full calldata are accepted for API symmetry
+/// with `deploy_code` but otherwise unused).
+pub fn call<T: EvmState>(
+    state: &mut T,
+    sender: &Address,
+    origin: &Address,
+    value: Option<U256>,
+    call_stack_depth: usize,
+    contract_address: &Address,
+    input: &[u8],
+    should_commit: bool,
+) -> Result<Vec<u8>, EvmError> {
+    if call_stack_depth > MAX_CALL_STACK_DEPTH {
+        return Err(EvmError::UnknownError);
+    }
+
+    if let Some(precompile) = builtins::precompile(contract_address) {
+        state.charge_gas(precompile.required_gas(input))?;
+        return precompile.run(input);
+    }
+
+    state.charge_gas(G_CALL + data_gas(input))?;
+
+    if state.code_at(contract_address)?.map(|code| !code.is_empty()).unwrap_or(false) {
+        return Err(EvmError::BytecodeNotSupported);
+    }
+
+    if let Some(value) = value {
+        if !value.is_zero() {
+            state.transfer_balance(sender, contract_address, value)?;
+        }
+    }
+
+    let _ = (origin, should_commit, input);
+    Ok(vec![])
+}
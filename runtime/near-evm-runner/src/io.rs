@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::AccountId;
+use near_store::TrieUpdate;
+
+use crate::errors::EvmError;
+
+/// A storage value that may be expensive to materialize. Exposing `len`
+/// separately from `to_vec` lets callers that only need to validate a
+/// size (e.g. "is this 32 bytes?") do so without copying.
+pub trait StorageIntermediate {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn to_vec(&self) -> Vec<u8>;
+}
+
+impl StorageIntermediate for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Vec::is_empty(self)
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+/// The storage backend `EvmState` is generic over. Every key/value is a
+/// flat byte string; higher layers (accounts, code, contract storage
+/// slots) are responsible for namespacing their own keys. Implementing
+/// this is all that's needed to embed the EVM core somewhere other than
+/// a NEAR trie, e.g. in-memory for tests or a standalone/RPC runner.
+pub trait IO {
+    type StorageValue: StorageIntermediate;
+
+    /// Returns `Err(EvmError::StateCorrupt)` if the backing store itself
+    /// failed to answer the read (e.g. a corrupt trie node) — distinct
+    /// from `Ok(None)`, which means the key is genuinely absent. Callers
+    /// must not collapse the two, or a transient storage failure reads
+    /// as "account doesn't exist yet" and mints/loses state.
+    fn read_storage(&self, key: &[u8]) -> Result<Option<Self::StorageValue>, EvmError>;
+    fn write_storage(&mut self, key: &[u8], value: &[u8]);
+    fn remove_storage(&mut self, key: &[u8]);
+    /// Returns `false` both for an absent key and for a read the backing
+    /// store failed to answer; only `read_storage` tells the two apart.
+    fn storage_has_key(&self, key: &[u8]) -> bool;
+}
+
+/// Adapts a NEAR `TrieUpdate` to `IO`, for production use. Every key is
+/// stored as its own `ContractData` entry scoped to the account the EVM
+/// contract is deployed at.
+pub struct TrieUpdateIo<'a> {
+    trie_update: &'a mut TrieUpdate,
+    account_id: AccountId,
+}
+
+impl<'a> TrieUpdateIo<'a> {
+    pub fn new(trie_update: &'a mut TrieUpdate, account_id: AccountId) -> Self {
+        Self { trie_update, account_id }
+    }
+
+    fn trie_key(&self, key: &[u8]) -> TrieKey {
+        TrieKey::ContractData { account_id: self.account_id.clone(), key: key.to_vec() }
+    }
+}
+
+impl<'a> IO for TrieUpdateIo<'a> {
+    type StorageValue = Vec<u8>;
+
+    fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, EvmError> {
+        self.trie_update.get(&self.trie_key(key)).map_err(|_| EvmError::StateCorrupt)
+    }
+
+    fn write_storage(&mut self, key: &[u8], value: &[u8]) {
+        self.trie_update.set(self.trie_key(key), value.to_vec());
+    }
+
+    fn remove_storage(&mut self, key: &[u8]) {
+        self.trie_update.remove(&self.trie_key(key));
+    }
+
+    fn storage_has_key(&self, key: &[u8]) -> bool {
+        matches!(self.read_storage(key), Ok(Some(_)))
+    }
+}
+
+/// In-memory `IO` backend, for unit tests and standalone/RPC execution
+/// that has no trie to write into.
+#[derive(Default)]
+pub struct MemoryIo {
+    storage: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl IO for MemoryIo {
+    type StorageValue = Vec<u8>;
+
+    fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, EvmError> {
+        Ok(self.storage.get(key).cloned())
+    }
+
+    fn write_storage(&mut self, key: &[u8], value: &[u8]) {
+        self.storage.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove_storage(&mut self, key: &[u8]) {
+        self.storage.remove(key);
+    }
+
+    fn storage_has_key(&self, key: &[u8]) -> bool {
+        self.storage.contains_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_io_round_trips_storage() {
+        let mut io = MemoryIo::default();
+        assert!(!io.storage_has_key(b"key"));
+        assert!(io.read_storage(b"key").unwrap().is_none());
+
+        io.write_storage(b"key", b"value");
+        assert!(io.storage_has_key(b"key"));
+        assert_eq!(io.read_storage(b"key").unwrap().unwrap().to_vec(), b"value".to_vec());
+
+        io.remove_storage(b"key");
+        assert!(!io.storage_has_key(b"key"));
+        assert!(io.read_storage(b"key").unwrap().is_none());
+    }
+}
@@ -2,95 +2,301 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use ethereum_types::{Address, H160, U256};
 use evm::CreateContractAddress;
 
-use near_primitives::types::{AccountId, Balance};
+use near_primitives::types::{AccountId, Balance, Gas};
 use near_store::TrieUpdate;
 use near_vm_errors::VMError;
-use near_vm_logic::VMOutcome;
+use near_vm_logic::{ReturnData, VMOutcome};
 
 use crate::errors::EvmError;
 use crate::evm_state::{EvmAccount, EvmState, StateStore};
-use crate::types::{GetCodeArgs, GetStorageAtArgs, WithdrawNearArgs};
-use near_primitives::trie_key::TrieKey;
+use crate::io::{StorageIntermediate, TrieUpdateIo, IO};
+use crate::near_ext::{NearPromiseHandler, PromiseHandler};
+use crate::types::{GetCodeArgs, GetStorageAtArgs, SetPausedFlagsArgs, WithdrawNearArgs};
 
 mod builtins;
 mod errors;
 mod evm_state;
 mod interpreter;
+pub mod io;
+mod meta_tx;
 mod near_ext;
 pub mod types;
 pub mod utils;
 
-pub struct EvmContext<'a> {
-    trie_update: &'a mut TrieUpdate,
+/// The EIP-155 chain id meta-transactions must be signed for. Submitting a
+/// transaction signed for any other network is rejected rather than
+/// silently executed, so a signature can't be replayed across deployments.
+/// Legacy (pre-EIP-155) transactions carry no chain id and are accepted
+/// without this check.
+pub const CHAIN_ID: u64 = 1_313_161_556;
+
+/// Tags prefixed onto the flat storage key so accounts, code and storage
+/// slots (all addressed by the same 20-byte Ethereum address) don't
+/// collide with one another.
+const ACCOUNT_KEY_TAG: u8 = 0;
+const CODE_KEY_TAG: u8 = 1;
+const STORAGE_KEY_TAG: u8 = 2;
+const TOTAL_SUPPLY_KEY: [u8; 1] = [3];
+const PAUSED_MASK_KEY: [u8; 1] = [4];
+
+/// Bits of the admin-set paused mask, matching `set_paused_flags`' single
+/// byte argument.
+pub const DEPOSIT_PAUSED: u8 = 1 << 0;
+pub const WITHDRAW_PAUSED: u8 = 1 << 1;
+
+fn account_trie_key(address: &Address) -> Vec<u8> {
+    let mut key = vec![ACCOUNT_KEY_TAG];
+    key.extend_from_slice(&address.0);
+    key
+}
+
+fn code_trie_key(address: &Address) -> Vec<u8> {
+    let mut key = vec![CODE_KEY_TAG];
+    key.extend_from_slice(&address.0);
+    key
+}
+
+fn storage_trie_key(key: &[u8; 52]) -> Vec<u8> {
+    let mut trie_key = vec![STORAGE_KEY_TAG];
+    trie_key.extend_from_slice(key);
+    trie_key
+}
+
+pub struct EvmContext<'a, I: IO> {
+    io: &'a mut I,
+    promise_handler: &'a mut dyn PromiseHandler,
     account_id: AccountId,
     predecessor_id: AccountId,
     attached_deposit: Balance,
+    /// Stack of in-flight substates, outermost first. Index 0 is the
+    /// transaction-level substate; `checkpoint()` pushes a new layer,
+    /// `commit()`/`rollback_to()` fold it into or discard it from its
+    /// parent.
+    substates: Vec<StateStore>,
+    evm_gas_limit: u64,
+    evm_gas_used: u64,
 }
 
-impl<'a> EvmState for EvmContext<'a> {
-    fn code_at(&self, address: &H160) -> Option<Vec<u8>> {
-        unimplemented!()
+impl<'a, I: IO> EvmState for EvmContext<'a, I> {
+    fn code_at(&self, address: &H160) -> Result<Option<Vec<u8>>, EvmError> {
+        for overlay in self.substates.iter().rev() {
+            if let Some(code) = overlay.codes.get(address) {
+                return Ok(Some(code.clone()));
+            }
+        }
+        Ok(self.io.read_storage(&code_trie_key(address))?.map(|value| value.to_vec()))
     }
 
-    fn set_code(&mut self, address: &H160, bytecode: &[u8]) {
-        unimplemented!()
+    fn set_code(&mut self, address: &H160, bytecode: &[u8]) -> Result<(), EvmError> {
+        self.current_substate_mut().codes.insert(*address, bytecode.to_vec());
+        Ok(())
     }
 
-    fn set_account(&mut self, address: &Address, account: &EvmAccount) {
-        self.trie_update.set(
-            TrieKey::ContractData { account_id: self.account_id.clone(), key: address.0.to_vec() },
-            account.try_to_vec().expect("Failed to serialize"),
-        )
+    fn set_account(&mut self, address: &Address, account: &EvmAccount) -> Result<(), EvmError> {
+        self.current_substate_mut().accounts.insert(*address, account.clone());
+        Ok(())
     }
 
-    fn get_account(&self, address: &Address) -> EvmAccount {
-        // TODO: handle error propagation?
-        self.trie_update
-            .get(&TrieKey::ContractData {
-                account_id: self.account_id.clone(),
-                key: address.0.to_vec(),
-            })
-            .unwrap_or_else(|_| None)
-            .map(|value| EvmAccount::try_from_slice(&value))
-            .unwrap_or_else(|| Ok(EvmAccount::default()))
-            .unwrap_or_else(|_| EvmAccount::default())
+    fn get_account(&self, address: &Address) -> Result<EvmAccount, EvmError> {
+        for overlay in self.substates.iter().rev() {
+            if let Some(account) = overlay.accounts.get(address) {
+                return Ok(account.clone());
+            }
+        }
+        match self.io.read_storage(&account_trie_key(address))? {
+            None => Ok(EvmAccount::default()),
+            Some(value) => {
+                EvmAccount::try_from_slice(&value.to_vec()).map_err(|_| EvmError::StateCorrupt)
+            }
+        }
     }
 
-    fn _read_contract_storage(&self, key: [u8; 52]) -> Option<[u8; 32]> {
-        unimplemented!()
+    fn _read_contract_storage(&self, key: [u8; 52]) -> Result<Option<[u8; 32]>, EvmError> {
+        for overlay in self.substates.iter().rev() {
+            if let Some(value) = overlay.storages.get(&key) {
+                return Ok(Some(*value));
+            }
+        }
+        match self.io.read_storage(&storage_trie_key(&key))? {
+            None => Ok(None),
+            Some(value) => {
+                if value.len() != 32 {
+                    return Err(EvmError::StateCorrupt);
+                }
+                let mut result = [0u8; 32];
+                result.copy_from_slice(&value.to_vec());
+                Ok(Some(result))
+            }
+        }
     }
 
-    fn _set_contract_storage(&mut self, key: [u8; 52], value: [u8; 32]) -> Option<[u8; 32]> {
-        unimplemented!()
+    fn _set_contract_storage(
+        &mut self,
+        key: [u8; 52],
+        value: [u8; 32],
+    ) -> Result<Option<[u8; 32]>, EvmError> {
+        let previous = self._read_contract_storage(key)?;
+        self.current_substate_mut().storages.insert(key, value);
+        Ok(previous)
     }
 
-    fn commit_changes(&mut self, _other: &StateStore) {
-        unimplemented!()
+    fn commit_changes(&mut self, other: &StateStore) -> Result<(), EvmError> {
+        for (address, account) in other.accounts.iter() {
+            self.io.write_storage(
+                &account_trie_key(address),
+                &account.try_to_vec().map_err(|_| EvmError::StateCorrupt)?,
+            );
+        }
+        for (address, code) in other.codes.iter() {
+            self.io.write_storage(&code_trie_key(address), code);
+        }
+        for (key, value) in other.storages.iter() {
+            self.io.write_storage(&storage_trie_key(key), value);
+        }
+        if let Some(total_supply) = other.total_supply {
+            let mut bytes = [0u8; 32];
+            total_supply.to_big_endian(&mut bytes);
+            self.io.write_storage(&TOTAL_SUPPLY_KEY, &bytes);
+        }
+        if let Some(paused_mask) = other.paused_mask {
+            self.io.write_storage(&PAUSED_MASK_KEY, &[paused_mask]);
+        }
+        Ok(())
     }
 
-    fn recreate(&mut self, _address: [u8; 20]) {
-        unimplemented!()
+    fn charge_gas(&mut self, amount: u64) -> Result<(), EvmError> {
+        self.evm_gas_used = self.evm_gas_used.saturating_add(amount);
+        if self.evm_gas_used > self.evm_gas_limit {
+            Err(EvmError::OutOfGas)
+        } else {
+            Ok(())
+        }
     }
 }
 
-impl<'a> EvmContext<'a> {
+impl<'a, I: IO> EvmContext<'a, I> {
     pub fn new(
-        state_update: &'a mut TrieUpdate,
+        io: &'a mut I,
+        promise_handler: &'a mut dyn PromiseHandler,
         account_id: AccountId,
         predecessor_id: AccountId,
         attached_deposit: Balance,
+        near_gas: Gas,
     ) -> Self {
         Self {
-            trie_update: state_update,
+            io,
+            promise_handler,
             account_id,
-            predecessor_id: predecessor_id,
+            predecessor_id,
             attached_deposit,
+            substates: vec![StateStore::new()],
+            evm_gas_limit: utils::near_gas_to_evm_gas(near_gas),
+            evm_gas_used: 0,
+        }
+    }
+
+    fn total_supply(&self) -> Result<U256, EvmError> {
+        for overlay in self.substates.iter().rev() {
+            if let Some(total_supply) = overlay.total_supply {
+                return Ok(total_supply);
+            }
+        }
+        Ok(self
+            .io
+            .read_storage(&TOTAL_SUPPLY_KEY)?
+            .map(|value| U256::from_big_endian(&value.to_vec()))
+            .unwrap_or_else(U256::zero))
+    }
+
+    fn set_total_supply(&mut self, value: U256) {
+        self.current_substate_mut().total_supply = Some(value);
+    }
+
+    fn paused_mask(&self) -> Result<u8, EvmError> {
+        for overlay in self.substates.iter().rev() {
+            if let Some(mask) = overlay.paused_mask {
+                return Ok(mask);
+            }
+        }
+        match self.io.read_storage(&PAUSED_MASK_KEY)? {
+            None => Ok(0),
+            Some(value) if value.len() == 1 => Ok(value.to_vec()[0]),
+            Some(_) => Err(EvmError::StateCorrupt),
+        }
+    }
+
+    /// Only the EVM contract's own account (i.e. a self-call, the usual
+    /// convention for privileged NEAR contract methods) may change the
+    /// paused mask.
+    pub fn set_paused_flags(&mut self, args: Vec<u8>) -> Result<(), EvmError> {
+        if self.predecessor_id != self.account_id {
+            return Err(EvmError::AdminRequired);
+        }
+        let args =
+            SetPausedFlagsArgs::try_from_slice(&args).map_err(|_| EvmError::ArgumentParseError)?;
+        self.current_substate_mut().paused_mask = Some(args.mask);
+        Ok(())
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.evm_gas_used
+    }
+
+    /// Logs accrued in the transaction-level substate, for surfacing in
+    /// the `VMOutcome` once execution has finished.
+    pub fn logs(&self) -> &[crate::evm_state::Log] {
+        &self.substates[0].logs
+    }
+
+    /// Flushes every change accumulated in the outermost substate down
+    /// into the backing `IO`, then fires any `withdraw_near` transfers
+    /// that were queued along the way. Must be called once, after the
+    /// top-level method invoked on this context has returned
+    /// successfully. The transfers only go out once `commit_changes` has
+    /// succeeded, so a promise is never queued for a balance decrement
+    /// that didn't actually stick.
+    pub fn finalize(&mut self) -> Result<(), EvmError> {
+        let base = self.substates[0].clone();
+        self.commit_changes(&base)?;
+        for (recipient, amount) in base.pending_transfers {
+            self.promise_handler.transfer(&recipient, amount);
+        }
+        Ok(())
+    }
+
+    fn current_substate_mut(&mut self) -> &mut StateStore {
+        self.substates.last_mut().expect("substate stack is never empty")
+    }
+
+    /// Opens a new speculative layer on top of the current state and
+    /// returns the index of the layer below it, to later pass to
+    /// `rollback_to`.
+    pub fn checkpoint(&mut self) -> usize {
+        let idx = self.substates.len() - 1;
+        self.substates.push(StateStore::new());
+        idx
+    }
+
+    /// Discards every substate layer above `idx`, undoing all writes made
+    /// since the matching `checkpoint()` call (e.g. on `REVERT` or
+    /// out-of-gas).
+    pub fn rollback_to(&mut self, idx: usize) {
+        self.substates.truncate(idx + 1);
+    }
+
+    /// Folds the top substate layer into its parent, keeping its writes
+    /// but collapsing the checkpoint.
+    pub fn commit(&mut self) {
+        if self.substates.len() > 1 {
+            let top = self.substates.pop().expect("checked len > 1 above");
+            self.current_substate_mut().accrue(top);
         }
     }
 
     pub fn deploy_code(&mut self, bytecode: Vec<u8>) -> Result<Address, EvmError> {
         let sender = utils::near_account_id_to_evm_address(&self.predecessor_id);
-        interpreter::deploy_code(
+        let checkpoint = self.checkpoint();
+        let result = interpreter::deploy_code(
             self,
             &sender,
             &sender,
@@ -99,7 +305,8 @@ impl<'a> EvmContext<'a> {
             CreateContractAddress::FromSenderAndNonce,
             true,
             &bytecode,
-        )
+        );
+        self.finish_checkpoint(checkpoint, result)
     }
 
     pub fn call_function(&mut self, args: Vec<u8>) -> Result<Vec<u8>, EvmError> {
@@ -108,61 +315,161 @@ impl<'a> EvmContext<'a> {
         let sender = utils::near_account_id_to_evm_address(&self.predecessor_id);
         let value =
             if self.attached_deposit == 0 { None } else { Some(U256::from(self.attached_deposit)) };
-        interpreter::call(self, &sender, &sender, value, 0, &contract_address, &input, true)
-            .map(|rd| rd.to_vec())
+        let checkpoint = self.checkpoint();
+        let result =
+            interpreter::call(self, &sender, &sender, value, 0, &contract_address, &input, true)
+                .map(|rd| rd.to_vec());
+        self.finish_checkpoint(checkpoint, result)
+    }
+
+    /// Commits the substate opened by `checkpoint` on success, or rolls
+    /// it back (discarding every write made since, e.g. on `REVERT` or
+    /// out-of-gas) on failure.
+    fn finish_checkpoint<T>(
+        &mut self,
+        checkpoint: usize,
+        result: Result<T, EvmError>,
+    ) -> Result<T, EvmError> {
+        match result {
+            Ok(value) => {
+                self.commit();
+                Ok(value)
+            }
+            Err(err) => {
+                self.rollback_to(checkpoint);
+                Err(err)
+            }
+        }
     }
 
     pub fn get_code(&self, args: Vec<u8>) -> Result<Vec<u8>, EvmError> {
         let args = GetCodeArgs::try_from_slice(&args).map_err(|_| EvmError::ArgumentParseError)?;
-        Ok(self.code_at(&Address::from_slice(&args.address)).unwrap_or(vec![]))
+        Ok(self.code_at(&Address::from_slice(&args.address))?.unwrap_or_else(Vec::new))
     }
 
     pub fn get_storage_at(&self, args: Vec<u8>) -> Result<Vec<u8>, EvmError> {
         let args =
             GetStorageAtArgs::try_from_slice(&args).map_err(|_| EvmError::ArgumentParseError)?;
         Ok(self
-            .read_contract_storage(&Address::from_slice(&args.address), args.key)
+            .read_contract_storage(&Address::from_slice(&args.address), args.key)?
             .unwrap_or([0u8; 32])
             .to_vec())
     }
 
     pub fn get_balance(&self, args: Vec<u8>) -> Result<U256, EvmError> {
-        Ok(self.balance_of(&Address::from_slice(&args)))
+        self.balance_of(&Address::from_slice(&args))
     }
 
     pub fn deposit_near(&mut self, args: Vec<u8>) -> Result<U256, EvmError> {
+        if self.paused_mask()? & DEPOSIT_PAUSED != 0 {
+            return Err(EvmError::BridgePaused);
+        }
         if self.attached_deposit == 0 {
             return Err(EvmError::MissingDeposit);
         }
         let address = Address::from_slice(&args);
-        self.add_balance(&address, U256::from(self.attached_deposit));
-        Ok(self.balance_of(&address))
+        let deposit = U256::from(self.attached_deposit);
+        self.add_balance(&address, deposit)?;
+        let total_supply = self.total_supply()?;
+        self.set_total_supply(total_supply + deposit);
+        self.balance_of(&address)
     }
 
     pub fn withdraw_near(&mut self, args: Vec<u8>) -> Result<(), EvmError> {
+        if self.paused_mask()? & WITHDRAW_PAUSED != 0 {
+            return Err(EvmError::BridgePaused);
+        }
         let args =
             WithdrawNearArgs::try_from_slice(&args).map_err(|_| EvmError::ArgumentParseError)?;
+        if !near_primitives::utils::is_valid_account_id(args.account_id.as_bytes()) {
+            return Err(EvmError::ArgumentParseError);
+        }
         let sender = utils::near_account_id_to_evm_address(&self.predecessor_id);
         let amount = U256::from(args.amount);
-        if amount > self.balance_of(&sender) {
+        if amount > self.balance_of(&sender)? {
             return Err(EvmError::InsufficientFunds);
         }
-        self.sub_balance(&sender, amount);
-        // TODO: add outgoing promise.
+        self.sub_balance(&sender, amount)?;
+        let total_supply = self.total_supply()?;
+        if amount > total_supply {
+            // The bridge's own books are inconsistent; abort rather than
+            // let more leave than was ever deposited.
+            return Err(EvmError::StateCorrupt);
+        }
+        self.set_total_supply(total_supply - amount);
+        // Queued, not fired immediately: see `StateStore::pending_transfers`.
+        // `finalize` is what actually hands this to the `PromiseHandler`,
+        // once the balance decrement above is durably committed.
+        self.current_substate_mut().pending_transfers.push((args.account_id, args.amount));
         Ok(())
     }
+
+    /// Executes an RLP-encoded, secp256k1-signed Ethereum transaction on
+    /// behalf of its recovered signer rather than `predecessor_id`. Lets a
+    /// relayer pay the NEAR gas for a transaction an EVM-native wallet
+    /// signed directly, with replay prevented by `EvmAccount::nonce`.
+    pub fn submit(&mut self, args: Vec<u8>) -> Result<Vec<u8>, EvmError> {
+        let tx = meta_tx::EthTransaction::decode(&args)?;
+        let sender = tx.recover_sender(CHAIN_ID)?;
+
+        let checkpoint = self.checkpoint();
+        let result = self.execute_meta_tx(&tx, &sender);
+        self.finish_checkpoint(checkpoint, result)
+    }
+
+    fn execute_meta_tx(
+        &mut self,
+        tx: &meta_tx::EthTransaction,
+        sender: &Address,
+    ) -> Result<Vec<u8>, EvmError> {
+        if self.get_account(sender)?.nonce != tx.nonce {
+            return Err(EvmError::InvalidNonce);
+        }
+
+        // `deploy_code` derives the CREATE address from `sender`'s current
+        // on-record nonce, which must still be the pre-increment value
+        // used to sign `tx` (Ethereum derives the address the same way).
+        // So run the inner call/create before bumping the stored nonce.
+        let result = match tx.to {
+            Some(to) => {
+                let value = if tx.value.is_zero() { None } else { Some(tx.value) };
+                interpreter::call(self, sender, sender, value, 0, &to, &tx.data, true)
+            }
+            None => interpreter::deploy_code(
+                self,
+                sender,
+                sender,
+                tx.value,
+                0,
+                CreateContractAddress::FromSenderAndNonce,
+                true,
+                &tx.data,
+            )
+            .map(|address| utils::address_to_vec(&address)),
+        };
+
+        // Re-read so the balance changes the call/create made are kept.
+        let mut account = self.get_account(sender)?;
+        account.nonce = account.nonce.saturating_add(U256::one());
+        self.set_account(sender, &account)?;
+        result
+    }
 }
 
 pub fn run_evm(
-    mut state_update: &mut TrieUpdate,
+    state_update: &mut TrieUpdate,
+    external: &mut dyn near_vm_logic::External,
     account_id: AccountId,
     predecessor_id: AccountId,
     attached_deposit: Balance,
+    near_gas: Gas,
     method_name: String,
     args: Vec<u8>,
 ) -> (Option<VMOutcome>, Option<VMError>) {
+    let mut io = TrieUpdateIo::new(state_update, account_id.clone());
+    let mut promise_handler = NearPromiseHandler::new(external);
     let mut context =
-        EvmContext::new(&mut state_update, account_id, predecessor_id, attached_deposit);
+        EvmContext::new(&mut io, &mut promise_handler, account_id, predecessor_id, attached_deposit, near_gas);
     let result = match method_name.as_str() {
         "deploy_code" => context.deploy_code(args).map(|address| utils::address_to_vec(&address)),
         "get_code" => context.get_code(args),
@@ -171,7 +478,94 @@ pub fn run_evm(
         "get_balance" => context.get_balance(args).map(|balance| utils::u256_to_vec(&balance)),
         "deposit_near" => context.deposit_near(args).map(|balance| utils::u256_to_vec(&balance)),
         "withdraw_near" => context.withdraw_near(args).map(|_| vec![]),
+        "set_paused_flags" => context.set_paused_flags(args).map(|_| vec![]),
+        "submit" => context.submit(args),
         _ => Err(EvmError::UnknownError),
     };
-    (None, None)
+
+    let result = result.and_then(|return_data| {
+        context.finalize()?;
+        Ok(return_data)
+    });
+
+    let burnt_gas = utils::evm_gas_to_near_gas(context.gas_used());
+    match result {
+        Ok(return_data) => {
+            let logs = context.logs().iter().map(log_to_string).collect();
+            (
+                Some(VMOutcome {
+                    balance: context.attached_deposit,
+                    storage_usage: 0,
+                    return_data: ReturnData::Value(return_data),
+                    burnt_gas,
+                    used_gas: burnt_gas,
+                    logs,
+                }),
+                None,
+            )
+        }
+        Err(err) => (None, Some(VMError::from(err))),
+    }
+}
+
+/// Renders an EVM log entry the way NEAR's `VMOutcome::logs` expects:
+/// a human-readable line, not a consensus-critical encoding.
+fn log_to_string(log: &crate::evm_state::Log) -> String {
+    let topics: Vec<String> =
+        log.topics.iter().map(|topic| format!("0x{}", utils::bytes_to_hex(topic))).collect();
+    format!(
+        "EVM log: address=0x{} topics=[{}] data=0x{}",
+        utils::bytes_to_hex(&log.address.0),
+        topics.join(", "),
+        utils::bytes_to_hex(&log.data)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::MemoryIo;
+
+    /// A `PromiseHandler` that ignores transfers, for tests that
+    /// don't have a real `near_vm_logic::External` to drive.
+    #[derive(Default)]
+    struct NoopPromiseHandler;
+
+    impl PromiseHandler for NoopPromiseHandler {
+        fn transfer(&mut self, _recipient: &AccountId, _amount: Balance) {}
+    }
+
+    fn context<'a>(
+        io: &'a mut MemoryIo,
+        promise_handler: &'a mut NoopPromiseHandler,
+    ) -> EvmContext<'a, MemoryIo> {
+        EvmContext::new(
+            io,
+            promise_handler,
+            "evm.near".parse().unwrap(),
+            "alice.near".parse().unwrap(),
+            0,
+            1_000_000_000,
+        )
+    }
+
+    #[test]
+    fn checkpoint_rollback_discards_writes() {
+        let mut io = MemoryIo::default();
+        let mut promise_handler = NoopPromiseHandler::default();
+        let mut context = context(&mut io, &mut promise_handler);
+
+        let address = Address::from_low_u64_be(1);
+        let account = EvmAccount { nonce: U256::from(1), balance: U256::from(100) };
+        context.set_account(&address, &account).unwrap();
+
+        let checkpoint = context.checkpoint();
+        context
+            .set_account(&address, &EvmAccount { nonce: U256::from(2), balance: U256::from(0) })
+            .unwrap();
+        assert_eq!(context.get_account(&address).unwrap().nonce, U256::from(2));
+
+        context.rollback_to(checkpoint);
+        assert_eq!(context.get_account(&address).unwrap(), account);
+    }
 }
@@ -0,0 +1,150 @@
+//! Decoding and signature recovery for relayer-submitted Ethereum
+//! transactions (`EvmContext::submit`). Kept separate from `interpreter`
+//! since this is about authenticating a transaction's sender, not
+//! executing one.
+
+use ethereum_types::{Address, U256};
+use sha3::{Digest, Keccak256};
+
+use crate::errors::EvmError;
+
+/// A decoded legacy (or EIP-155) signed Ethereum transaction.
+pub struct EthTransaction {
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas_limit: U256,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+    /// `Some` when `v` encodes an EIP-155 chain id (`v = chain_id * 2 + 35/36`).
+    pub chain_id: Option<u64>,
+}
+
+impl EthTransaction {
+    /// Decodes the RLP list `[nonce, gas_price, gas_limit, to, value, data, v, r, s]`.
+    /// `gas_price`/`gas_limit` are parsed but not charged against: the NEAR
+    /// gas the relayer attached is what pays for execution, same as any
+    /// other entry point.
+    pub fn decode(bytes: &[u8]) -> Result<Self, EvmError> {
+        let rlp = rlp::Rlp::new(bytes);
+        if !rlp.is_list() || rlp.item_count().map_err(|_| EvmError::ArgumentParseError)? != 9 {
+            return Err(EvmError::ArgumentParseError);
+        }
+        let bad = || EvmError::ArgumentParseError;
+        let nonce: U256 = rlp.val_at(0).map_err(|_| bad())?;
+        let gas_price: U256 = rlp.val_at(1).map_err(|_| bad())?;
+        let gas_limit: U256 = rlp.val_at(2).map_err(|_| bad())?;
+        let to_bytes: Vec<u8> = rlp.val_at(3).map_err(|_| bad())?;
+        let to = if to_bytes.is_empty() {
+            None
+        } else if to_bytes.len() == 20 {
+            Some(Address::from_slice(&to_bytes))
+        } else {
+            return Err(bad());
+        };
+        let value: U256 = rlp.val_at(4).map_err(|_| bad())?;
+        let data: Vec<u8> = rlp.val_at(5).map_err(|_| bad())?;
+        let v: u64 = rlp.val_at(6).map_err(|_| bad())?;
+        let r: U256 = rlp.val_at(7).map_err(|_| bad())?;
+        let s: U256 = rlp.val_at(8).map_err(|_| bad())?;
+
+        let chain_id = if v >= 35 { Some((v - 35) / 2) } else { None };
+
+        Ok(Self { nonce, gas_price, gas_limit, to, value, data, v, r, s, chain_id })
+    }
+
+    /// Recovers the signing address, checking the embedded chain id (if
+    /// any) against `expected_chain_id` first so a transaction meant for
+    /// another network can't be replayed here.
+    pub fn recover_sender(&self, expected_chain_id: u64) -> Result<Address, EvmError> {
+        if let Some(chain_id) = self.chain_id {
+            if chain_id != expected_chain_id {
+                return Err(EvmError::InvalidChainId);
+            }
+        }
+
+        let recovery_id = match self.chain_id {
+            Some(chain_id) => self.v - 35 - 2 * chain_id,
+            None => self.v.checked_sub(27).ok_or(EvmError::InvalidSignature)?,
+        };
+        let recovery_id = u8::try_from(recovery_id).map_err(|_| EvmError::InvalidSignature)?;
+        let recovery_id =
+            secp256k1::RecoveryId::parse(recovery_id).map_err(|_| EvmError::InvalidSignature)?;
+
+        let signature = {
+            let mut sig = [0u8; 64];
+            self.r.to_big_endian(&mut sig[..32]);
+            self.s.to_big_endian(&mut sig[32..]);
+            secp256k1::Signature::parse_standard(&sig).map_err(|_| EvmError::InvalidSignature)?
+        };
+
+        let message = {
+            let mut msg = [0u8; 32];
+            msg.copy_from_slice(&self.signing_hash());
+            secp256k1::Message::parse(&msg)
+        };
+
+        let public_key = secp256k1::recover(&message, &signature, &recovery_id)
+            .map_err(|_| EvmError::InvalidSignature)?;
+        let serialized = public_key.serialize();
+        let hash = Keccak256::digest(&serialized[1..]);
+        Ok(Address::from_slice(&hash[12..]))
+    }
+
+    /// The hash that was actually signed: the RLP-encoded transaction with
+    /// its signature fields replaced by `(chain_id, 0, 0)` for an EIP-155
+    /// transaction, or dropped entirely for a legacy one (EIP-155 sec 2).
+    fn signing_hash(&self) -> [u8; 32] {
+        let mut stream = rlp::RlpStream::new();
+        stream.begin_list(if self.chain_id.is_some() { 9 } else { 6 });
+        stream.append(&self.nonce);
+        stream.append(&self.gas_price);
+        stream.append(&self.gas_limit);
+        match &self.to {
+            Some(address) => {
+                stream.append(&address.0.to_vec());
+            }
+            None => {
+                stream.append_empty_data();
+            }
+        }
+        stream.append(&self.value);
+        stream.append(&self.data);
+        if let Some(chain_id) = self.chain_id {
+            stream.append(&chain_id);
+            stream.append(&0u8);
+            stream.append(&0u8);
+        }
+        let hash = Keccak256::digest(&stream.out());
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&hash);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `to` field that is non-empty but not exactly 20 bytes must be
+    /// rejected, not passed to `Address::from_slice` (which panics on a
+    /// length mismatch).
+    #[test]
+    fn decode_rejects_malformed_to_length() {
+        let mut stream = rlp::RlpStream::new_list(9);
+        stream.append(&U256::zero()); // nonce
+        stream.append(&U256::zero()); // gas_price
+        stream.append(&U256::zero()); // gas_limit
+        stream.append(&vec![0u8; 19]); // to: one byte short of a valid address
+        stream.append(&U256::zero()); // value
+        stream.append(&Vec::<u8>::new()); // data
+        stream.append(&27u64); // v
+        stream.append(&U256::one()); // r
+        stream.append(&U256::one()); // s
+
+        assert!(EthTransaction::decode(&stream.out()).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Bridges the NEAR runtime's promise API into the EVM core. Kept
+//! separate from `EvmContext` so it can depend on a narrow view of the
+//! environment (outgoing transfers) rather than the whole runtime context.
+
+use near_primitives::types::{AccountId, Balance};
+use near_vm_logic::External;
+
+/// Capability to move NEAR tokens out of the EVM contract's own account,
+/// backing the outgoing half of `withdraw_near`. Kept as a trait, rather
+/// than calling the runtime's promise host functions directly, so the
+/// EVM core stays testable without a real receipt-producing runtime
+/// behind it.
+pub trait PromiseHandler {
+    /// Queues an outgoing transfer of `amount` yoctoNEAR to `recipient`.
+    fn transfer(&mut self, recipient: &AccountId, amount: Balance);
+}
+
+/// Production `PromiseHandler`, backed by the runtime's own promise host
+/// functions. A single-action batch is all `withdraw_near` needs; there is
+/// no callback, so failures surface the usual way a NEAR transfer does (the
+/// receipt simply fails on the other end, it does not roll back this call).
+pub struct NearPromiseHandler<'a> {
+    external: &'a mut dyn External,
+}
+
+impl<'a> NearPromiseHandler<'a> {
+    pub fn new(external: &'a mut dyn External) -> Self {
+        Self { external }
+    }
+}
+
+impl<'a> PromiseHandler for NearPromiseHandler<'a> {
+    fn transfer(&mut self, recipient: &AccountId, amount: Balance) {
+        // `recipient` was already validated as a well-formed account id by
+        // `withdraw_near`, so batch creation/the transfer action can only
+        // fail on a host bug, not on caller input.
+        let promise_id = self
+            .external
+            .promise_batch_create(recipient)
+            .expect("promise_batch_create failed for a validated account id");
+        self.external
+            .promise_batch_action_transfer(promise_id, amount)
+            .expect("promise_batch_action_transfer failed");
+    }
+}
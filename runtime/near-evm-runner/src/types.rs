@@ -0,0 +1,24 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_primitives::types::{AccountId, Balance};
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct GetCodeArgs {
+    pub address: [u8; 20],
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct GetStorageAtArgs {
+    pub address: [u8; 20],
+    pub key: [u8; 32],
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct WithdrawNearArgs {
+    pub account_id: AccountId,
+    pub amount: Balance,
+}
+
+#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub struct SetPausedFlagsArgs {
+    pub mask: u8,
+}
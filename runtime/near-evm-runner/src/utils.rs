@@ -0,0 +1,41 @@
+use ethereum_types::{Address, U256};
+use near_primitives::types::{AccountId, Gas};
+use sha3::{Digest, Keccak256};
+
+/// NEAR gas is metered far more finely than EVM gas (Ethereum's own unit
+/// of account); this is the exchange rate used to translate between the
+/// two when a NEAR gas budget is turned into an EVM gas limit, and back
+/// again when reporting how much was actually burnt.
+pub const EVM_GAS_TO_NEAR_GAS: u64 = 100_000;
+
+pub fn near_gas_to_evm_gas(near_gas: Gas) -> u64 {
+    near_gas / EVM_GAS_TO_NEAR_GAS
+}
+
+pub fn evm_gas_to_near_gas(evm_gas: u64) -> Gas {
+    evm_gas.saturating_mul(EVM_GAS_TO_NEAR_GAS)
+}
+
+/// Derives a deterministic EVM address for a NEAR account id by hashing
+/// the account id string and taking the low 20 bytes, mirroring how an
+/// Ethereum address is derived from a public key hash.
+pub fn near_account_id_to_evm_address(account_id: &AccountId) -> Address {
+    let hash = Keccak256::digest(account_id.as_bytes());
+    Address::from_slice(&hash[12..32])
+}
+
+pub fn address_to_vec(address: &Address) -> Vec<u8> {
+    address.0.to_vec()
+}
+
+pub fn u256_to_vec(value: &U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes.to_vec()
+}
+
+/// Lowercase hex encoding with no `0x` prefix, for rendering raw bytes
+/// (addresses, topics, log data) into human-readable log lines.
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}